@@ -1,24 +1,32 @@
-use ahash::{HashMap, HashMapExt};
+mod parser;
+mod table;
+
 use anyhow::{ensure, Context, Result};
 use itertools::Itertools;
 use std::{
-    env, fmt,
+    env,
     fs::File,
     io::{prelude::*, BufReader, SeekFrom},
-    str, thread,
+    str,
+    sync::mpsc,
+    thread,
 };
-
-/// Max number of unique names in the input.
-const NUM_KEYS: usize = 10_000;
+use table::Table;
 
 /// Max length of input lines.
 const LINE_LEN: usize = 128;
 
+/// Size of each block read by a chunk's I/O thread.
+const BLOCK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Number of blocks in flight between the I/O thread and the parsing
+/// thread, including the one currently being filled/parsed.
+const NUM_BLOCK_BUFS: usize = 3;
+
 fn main() -> Result<()> {
     let args: Vec<_> = env::args().skip(1).collect();
-    ensure!(args.len() == 1, "expected filename");
+    let (delim, filename) = parse_args(&args)?;
 
-    let filename = &args[0];
     let file = File::open(filename).with_context(|| format!("couldn't open file {filename:?}"))?;
     let num_threads = thread::available_parallelism()?.into();
     let chunks = chunks(file, num_threads)?;
@@ -26,173 +34,180 @@ fn main() -> Result<()> {
     let mut threads = Vec::with_capacity(chunks.len());
     for ch in chunks {
         let file = File::open(filename)?;
-        let t = thread::spawn(move || chunk_stats(file, ch));
+        let t = thread::spawn(move || chunk_stats(file, ch, delim));
         threads.push(t);
     }
 
-    let mut stats = HashMap::<Vec<u8>, Stats>::with_capacity(NUM_KEYS);
+    let mut table = Table::new();
     for t in threads {
-        let chunk_stats = t.join().expect("thread panic")?;
-        for (k, st) in chunk_stats {
-            stats.entry(k).or_default().merge(st);
+        let chunk_table = t.join().expect("thread panic")?;
+        for (name, stats) in chunk_table.iter() {
+            table.merge(name, *stats)?;
         }
     }
-    print_stats(&stats)?;
+    print_stats(&table)?;
 
     Ok(())
 }
 
-/// Partition a file into exactly n chunks, each represented as `start..end`.
-///
-/// Chunk boundaries are always after a newline (except the first, and possibly
-/// the last).
+/// Parse `[--delim <char>] <filename>` from the command line, defaulting
+/// `delim` to `;` (the canonical 1BRC separator).
+fn parse_args(args: &[String]) -> Result<(u8, &str)> {
+    let mut delim = b';';
+    let mut filename = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--delim" {
+            let d = args.next().context("--delim requires a value")?;
+            ensure!(d.len() == 1, "--delim must be a single byte");
+            delim = d.as_bytes()[0];
+        } else {
+            ensure!(filename.is_none(), "expected exactly one filename");
+            filename = Some(arg.as_str());
+        }
+    }
+
+    let filename = filename.context("expected filename")?;
+    Ok((delim, filename))
+}
+
+/// Partition a file into at most n chunks, each represented as `start..end`.
 ///
-/// Chunks may be empty (unlikely for large files).
+/// Chunk boundaries always land right after a newline (except the first,
+/// and the last if the file doesn't end in one). Boundaries are clamped to
+/// the file's length and deduplicated, so the returned chunks are always
+/// strictly increasing and together cover every byte exactly once -- even
+/// for an empty file, a file with no trailing newline, or a `num_threads`
+/// greater than the number of lines in the file (in which case fewer than
+/// `n` chunks are returned).
 fn chunks(file: File, n: usize) -> Result<Vec<(u64, u64)>> {
     assert_ne!(n, 0);
     let len = file.metadata()?.len();
     let mut file = BufReader::new(file);
 
-    let mut boundaries = Vec::with_capacity(n);
+    let mut boundaries = Vec::with_capacity(n + 1);
     boundaries.push(0);
 
     let mut buf = Vec::with_capacity(LINE_LEN);
     let n = n as u64;
-    for i in 1..=n - 1 {
-        let offset = len * i / n;
+    for i in 1..n {
+        let offset = (len * i / n).min(len);
         file.seek(SeekFrom::Start(offset))?;
         buf.clear();
         file.read_until(b'\n', &mut buf)?;
-        boundaries.push(offset + buf.len() as u64);
+        boundaries.push((offset + buf.len() as u64).min(len));
     }
 
     boundaries.push(len);
+    boundaries.dedup();
 
     Ok(boundaries.into_iter().tuple_windows().collect())
 }
 
-fn chunk_stats(file: File, (start, end): (u64, u64)) -> Result<HashMap<Vec<u8>, Stats>> {
-    let mut stats = HashMap::with_capacity(NUM_KEYS);
+/// Read and parse a chunk of the file.
+///
+/// A dedicated I/O thread reads `start..end` in fixed-size blocks and hands
+/// them to this (the parsing) thread over a channel; a second channel sends
+/// emptied buffers back, so I/O overlaps with parsing and blocks are reused
+/// without reallocating. The parser walks each block in place, producing
+/// `&[u8]` slices directly into the block -- only a line that straddles a
+/// block boundary (carried over into the next block) and the hash-table
+/// insert path (for a never-before-seen name) copy any bytes.
+fn chunk_stats(file: File, (start, end): (u64, u64), delim: u8) -> Result<Table> {
+    let (filled_tx, filled_rx) = mpsc::sync_channel::<Vec<u8>>(NUM_BLOCK_BUFS);
+    let (free_tx, free_rx) = mpsc::sync_channel::<Vec<u8>>(NUM_BLOCK_BUFS);
+    for _ in 0..NUM_BLOCK_BUFS {
+        free_tx.send(Vec::with_capacity(BLOCK_SIZE)).unwrap();
+    }
 
-    let mut file = BufReader::new(file);
-    file.seek(SeekFrom::Start(start))?;
-
-    let mut curr_offset = start;
-    let mut line = Vec::<u8>::with_capacity(LINE_LEN);
-    while curr_offset < end {
-        line.clear();
-        let n = file.read_until(b'\n', &mut line)? as u64;
-        if n == 0 {
-            break;
-        }
-        curr_offset += n;
-        if line.ends_with(b"\n") {
-            line.pop();
+    let io_thread = thread::spawn(move || -> Result<()> {
+        let mut file = file;
+        file.seek(SeekFrom::Start(start))?;
+
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let Ok(mut buf) = free_rx.recv() else {
+                break;
+            };
+            let n = BLOCK_SIZE.min(remaining as usize);
+            buf.resize(n, 0);
+            file.read_exact(&mut buf)?;
+            remaining -= n as u64;
+
+            if filled_tx.send(buf).is_err() {
+                break;
+            }
         }
 
-        let (name, value) = split_once(&line, b';').context("expected semicolon")?;
-        let value = parse_f32(value).context("failed to parse special-case f32")?;
+        Ok(())
+    });
 
-        match stats.get_mut(name) {
-            None => {
-                stats.insert(name.to_owned(), Stats::singleton(value));
-            }
-            Some(st) => st.update(value),
-        }
+    let mut table = Table::new();
+    let mut carry = Vec::<u8>::with_capacity(LINE_LEN);
+    for block in &filled_rx {
+        parse_block(&block, delim, &mut carry, &mut table)?;
+        // Don't care if the I/O thread has already hung up: we're done
+        // reading either way.
+        let _ = free_tx.send(block);
     }
 
-    Ok(stats)
-}
+    io_thread.join().expect("I/O thread panicked")?;
+
+    // The last chunk of the file may not end in a newline.
+    if !carry.is_empty() {
+        parse_line(&carry, delim, &mut table)?;
+    }
 
-fn split_once(s: &[u8], delim: u8) -> Option<(&[u8], &[u8])> {
-    let i = s.iter().position(|&b| b == delim)?;
-    Some((&s[..i], &s[i + 1..]))
+    Ok(table)
 }
 
-fn parse_f32(mut s: &[u8]) -> Option<f32> {
-    let minus = if s[0] == b'-' {
-        s = &s[1..];
-        -1.
-    } else {
-        1.
-    };
-
-    let magnitude = match s.len() {
-        3 => {
-            if s[1] != b'.' {
-                return None;
+/// Parse every complete record in `block`, carrying any trailing partial
+/// line (one that's cut off by the block boundary) over in `carry` so it
+/// can be completed by the front of the next block.
+fn parse_block(block: &[u8], delim: u8, carry: &mut Vec<u8>, table: &mut Table) -> Result<()> {
+    let mut pos = 0;
+
+    if !carry.is_empty() {
+        match block.iter().position(|&b| b == b'\n') {
+            Some(nl) => {
+                carry.extend_from_slice(&block[..nl]);
+                parse_line(carry, delim, table)?;
+                carry.clear();
+                pos = nl + 1;
             }
-            let x = to_digit(s[0])? * 1.;
-            let y = to_digit(s[2])? * 0.1;
-            x + y
-        }
-        4 => {
-            if s[2] != b'.' {
-                return None;
+            None => {
+                // The whole block is still part of the same line.
+                carry.extend_from_slice(block);
+                return Ok(());
             }
-            let a = to_digit(s[0])? * 10.;
-            let b = to_digit(s[1])? * 1.;
-            let c = to_digit(s[3])? * 0.1;
-            a + b + c
-        }
-        _ => return None,
-    };
-
-    Some(minus * magnitude)
-}
-
-fn to_digit(c: u8) -> Option<f32> {
-    let d = (c as char).to_digit(10)?;
-    Some(d as f32)
-}
-
-/// Aggregated statistics for a single weather station.
-struct Stats {
-    min: f32,
-    max: f32,
-    sum: f32,
-    count: u32,
-}
-
-impl Default for Stats {
-    fn default() -> Self {
-        Self {
-            min: f32::MAX,
-            max: f32::MIN,
-            sum: 0.,
-            count: 0,
         }
     }
-}
 
-impl Stats {
-    fn singleton(value: f32) -> Self {
-        Self {
-            min: value,
-            max: value,
-            sum: value,
-            count: 1,
+    while pos < block.len() {
+        match block[pos..].iter().position(|&b| b == b'\n') {
+            Some(rel) => {
+                parse_line(&block[pos..pos + rel], delim, table)?;
+                pos += rel + 1;
+            }
+            None => {
+                carry.extend_from_slice(&block[pos..]);
+                break;
+            }
         }
     }
 
-    fn update(&mut self, value: f32) {
-        self.merge(Self::singleton(value))
-    }
-
-    fn merge(&mut self, other: Self) {
-        self.min = f32::min(self.min, other.min);
-        self.max = f32::max(self.max, other.max);
-        self.sum += other.sum;
-        self.count += other.count;
-    }
+    Ok(())
+}
 
-    fn avg(&self) -> f32 {
-        self.sum / self.count as f32
-    }
+fn parse_line(line: &[u8], delim: u8, table: &mut Table) -> Result<()> {
+    let record = parser::parse_record(delim, line)?;
+    table.update(record.name, record.value)?;
+    Ok(())
 }
 
-fn print_stats(stats: &HashMap<Vec<u8>, Stats>) -> Result<()> {
-    let mut pairs: Vec<_> = stats
+fn print_stats(table: &Table) -> Result<()> {
+    let mut pairs: Vec<_> = table
         .iter()
         .map(|(name, value)| anyhow::Ok((str::from_utf8(name)?, value)))
         .try_collect()?;
@@ -214,13 +229,76 @@ fn print_stats(stats: &HashMap<Vec<u8>, Stats>) -> Result<()> {
     Ok(())
 }
 
-impl fmt::Display for Stats {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Note: this rounds to nearest.
-        //
-        // The challenge rules say to round away from zero (the opposite of
-        // truncate), but their example code doesn't do what they say -- it
-        // rounds to nearest.
-        write!(f, "{:.1}/{:.1}/{:.1}", self.min, self.avg(), self.max)
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn temp_file(data: &[u8]) -> Result<std::path::PathBuf> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        let mut path = env::temp_dir();
+        path.push(format!("1brc-chunks-test-{}-{id}.txt", std::process::id()));
+        std::fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    /// Run the full partition-and-aggregate pipeline over `data` using
+    /// `num_threads` chunks, returning the merged result as sorted
+    /// `"name=min/mean/max"` strings for easy comparison.
+    fn run(data: &[u8], num_threads: usize) -> Result<Vec<String>> {
+        let path = temp_file(data)?;
+
+        let file = File::open(&path)?;
+        let chunks = chunks(file, num_threads)?;
+
+        let mut table = Table::new();
+        for ch in chunks {
+            let file = File::open(&path)?;
+            let chunk_table = chunk_stats(file, ch, b';')?;
+            for (name, stats) in chunk_table.iter() {
+                table.merge(name, *stats)?;
+            }
+        }
+
+        std::fs::remove_file(&path)?;
+
+        let mut lines: Vec<_> = table
+            .iter()
+            .map(|(name, stats)| format!("{}={stats}", str::from_utf8(name).unwrap()))
+            .collect();
+        lines.sort();
+        Ok(lines)
+    }
+
+    #[test]
+    fn one_line_file() -> Result<()> {
+        let data = b"foo;12.3\n";
+        assert_eq!(run(data, 1)?, run(data, 8)?);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_file() -> Result<()> {
+        let data = b"";
+        assert_eq!(run(data, 1)?, Vec::<String>::new());
+        assert_eq!(run(data, 1)?, run(data, 8)?);
+        Ok(())
+    }
+
+    #[test]
+    fn no_trailing_newline() -> Result<()> {
+        let data = b"foo;12.3\nbar;-4.5";
+        assert_eq!(run(data, 1)?, run(data, 8)?);
+        Ok(())
+    }
+
+    #[test]
+    fn more_threads_than_lines() -> Result<()> {
+        let data = b"foo;1.0\nbar;2.0\nfoo;3.0\n";
+        assert_eq!(run(data, 1)?, run(data, 16)?);
+        Ok(())
     }
 }
@@ -0,0 +1,121 @@
+//! A configurable, nom-based line parser.
+//!
+//! `main`'s original `split_once`/`parse_f32` pair hard-coded a `;`
+//! delimiter and exactly one fractional digit, so any other `key;value`
+//! numeric dataset was silently rejected. This parser instead accepts any
+//! single-byte delimiter and any number of fractional digits, normalizing
+//! every value to [`SCALE`] fractional digits so records of differing
+//! precision can still be summed together, and on malformed input it
+//! reports a precise byte offset instead of one opaque message.
+
+use std::fmt;
+
+use nom::{
+    bytes::complete::is_not,
+    character::complete::{char, digit0, digit1, space0},
+    combinator::{all_consuming, opt},
+    error::{context, VerboseError, VerboseErrorKind},
+    sequence::tuple,
+    IResult,
+};
+
+/// Number of fractional digits every parsed value is normalized to.
+pub const SCALE: u32 = 4;
+
+/// A parsed `name<delim>value` record. `value` is scaled so that it
+/// represents the number times `10^SCALE`.
+pub struct Record<'a> {
+    pub name: &'a [u8],
+    pub value: i64,
+}
+
+type NomError<'a> = VerboseError<&'a [u8]>;
+
+/// Parse one `name<delim>value` line, using `delim` as the separator
+/// (the canonical 1BRC format uses `;`).
+///
+/// The whole line must be consumed -- trailing garbage (a stray `\r` from
+/// CRLF input, or junk after the number) is a parse error, not silently
+/// truncated.
+pub fn parse_record(delim: u8, line: &[u8]) -> Result<Record<'_>, ParseError> {
+    match all_consuming(record(delim))(line) {
+        Ok((_rest, rec)) => Ok(rec),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(ParseError::from_nom(line, e)),
+        Err(nom::Err::Incomplete(_)) => unreachable!("only ever called on a complete line"),
+    }
+}
+
+fn record(delim: u8) -> impl FnMut(&[u8]) -> IResult<&[u8], Record<'_>, NomError<'_>> {
+    move |input| {
+        let (input, name) = context(
+            "expected a name followed by the delimiter",
+            is_not([delim].as_slice()),
+        )(input)?;
+        let (input, _) = char(delim as char)(input)?;
+        let (input, _) = space0(input)?;
+        let (input, (neg, int_part, frac_part)) = context(
+            "expected a (possibly signed, possibly fractional) number",
+            tuple((opt(char('-')), digit1, opt(fraction))),
+        )(input)?;
+        let (input, _) = space0(input)?;
+
+        let frac_part = frac_part.unwrap_or(&[][..]);
+        let mut value: i64 = 0;
+        for &d in int_part.iter().chain(frac_part) {
+            value = value * 10 + i64::from(d - b'0');
+        }
+
+        // Normalize to SCALE fractional digits, so e.g. "1.5" and "1.50"
+        // land on the same value and can be summed together.
+        let observed = frac_part.len() as u32;
+        value = match observed.cmp(&SCALE) {
+            std::cmp::Ordering::Less => value * 10i64.pow(SCALE - observed),
+            std::cmp::Ordering::Equal => value,
+            std::cmp::Ordering::Greater => value / 10i64.pow(observed - SCALE),
+        };
+        if neg.is_some() {
+            value = -value;
+        }
+
+        Ok((input, Record { name, value }))
+    }
+}
+
+fn fraction(input: &[u8]) -> IResult<&[u8], &[u8], NomError<'_>> {
+    let (input, _) = char('.')(input)?;
+    digit0(input)
+}
+
+/// A malformed record, with the byte offset into the line where parsing
+/// gave up.
+#[derive(Debug)]
+pub struct ParseError {
+    pub offset: usize,
+    message: String,
+}
+
+impl ParseError {
+    fn from_nom(line: &[u8], e: NomError<'_>) -> Self {
+        let (offset, message) = match e.errors.first() {
+            Some((rest, kind)) => {
+                let offset = line.len() - rest.len();
+                let message = match kind {
+                    VerboseErrorKind::Context(ctx) => ctx.to_string(),
+                    VerboseErrorKind::Char(c) => format!("expected {c:?}"),
+                    VerboseErrorKind::Nom(kind) => format!("{kind:?}"),
+                };
+                (offset, message)
+            }
+            None => (line.len(), "malformed record".to_string()),
+        };
+        Self { offset, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at byte offset {})", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
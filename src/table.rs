@@ -0,0 +1,208 @@
+//! A purpose-built aggregation table, keyed on raw station-name bytes.
+//!
+//! A plain `HashMap<Vec<u8>, Stats>` allocates an owned `Vec<u8>` for every
+//! new station, and the final cross-thread merge has to re-hash and
+//! re-allocate every key again. [`Table`] instead uses open addressing with
+//! linear probing and an inline key buffer, so once the table has warmed up
+//! (seen every station once), inserts are allocation-free.
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::parser::SCALE;
+use crate::LINE_LEN;
+
+/// Number of slots in the table: a power of two comfortably above the
+/// ~10,000 distinct station names expected in the input, to keep the load
+/// factor -- and thus probe lengths -- low.
+const NUM_SLOTS: usize = 1 << 15;
+
+/// Open-addressing hash table mapping station name to [`Stats`].
+pub struct Table {
+    slots: Vec<Slot>,
+}
+
+#[derive(Clone)]
+struct Slot {
+    occupied: bool,
+    key: [u8; LINE_LEN],
+    key_len: usize,
+    stats: Stats,
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            occupied: false,
+            key: [0; LINE_LEN],
+            key_len: 0,
+            stats: Stats::default(),
+        }
+    }
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![Slot::default(); NUM_SLOTS],
+        }
+    }
+
+    /// Fold `value` into the entry for `name`, creating it if `name` hasn't
+    /// been seen before.
+    ///
+    /// Errors if `name` is longer than `LINE_LEN` bytes, or if the table is
+    /// already full of other names.
+    pub fn update(&mut self, name: &[u8], value: i64) -> Result<()> {
+        let i = self.find_slot(name)?;
+        let slot = &mut self.slots[i];
+        if slot.occupied {
+            slot.stats.update(value);
+        } else {
+            Self::occupy(slot, name, Stats::singleton(value))?;
+        }
+        Ok(())
+    }
+
+    /// Merge another table's already-aggregated [`Stats`] for `name` into
+    /// this one, creating the entry if `name` hasn't been seen before.
+    ///
+    /// Errors if `name` is longer than `LINE_LEN` bytes, or if the table is
+    /// already full of other names.
+    pub fn merge(&mut self, name: &[u8], stats: Stats) -> Result<()> {
+        let i = self.find_slot(name)?;
+        let slot = &mut self.slots[i];
+        if slot.occupied {
+            slot.stats.merge(stats);
+        } else {
+            Self::occupy(slot, name, stats)?;
+        }
+        Ok(())
+    }
+
+    fn occupy(slot: &mut Slot, name: &[u8], stats: Stats) -> Result<()> {
+        ensure!(
+            name.len() <= LINE_LEN,
+            "station name is {} bytes long, over the {LINE_LEN}-byte limit",
+            name.len()
+        );
+        slot.occupied = true;
+        slot.key[..name.len()].copy_from_slice(name);
+        slot.key_len = name.len();
+        slot.stats = stats;
+        Ok(())
+    }
+
+    /// Find `name`'s slot, or the empty slot it should go in.
+    ///
+    /// Errors instead of probing forever if every slot is already occupied
+    /// by some other name.
+    fn find_slot(&self, name: &[u8]) -> Result<usize> {
+        let mut i = hash(name) as usize & (NUM_SLOTS - 1);
+        for _ in 0..NUM_SLOTS {
+            let slot = &self.slots[i];
+            if !slot.occupied || &slot.key[..slot.key_len] == name {
+                return Ok(i);
+            }
+            i = (i + 1) & (NUM_SLOTS - 1);
+        }
+        bail!("aggregation table is full: more than {NUM_SLOTS} distinct names");
+    }
+
+    /// Iterate over every occupied slot, as `(name, stats)`.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &Stats)> {
+        self.slots
+            .iter()
+            .filter(|slot| slot.occupied)
+            .map(|slot| (&slot.key[..slot.key_len], &slot.stats))
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FNV-1a hash over raw bytes: fast, and good enough for short ASCII
+/// station names.
+fn hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut h = FNV_OFFSET;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(FNV_PRIME);
+    }
+    h
+}
+
+/// Aggregated statistics for a single weather station.
+///
+/// `min`, `max`, and `sum` are all scaled by `10^SCALE` (see
+/// [`crate::parser`]), so the hot path stays integer-only and summation
+/// doesn't drift over billions of rows.
+#[derive(Clone, Copy)]
+pub struct Stats {
+    min: i64,
+    max: i64,
+    sum: i64,
+    count: u32,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            min: i64::MAX,
+            max: i64::MIN,
+            sum: 0,
+            count: 0,
+        }
+    }
+}
+
+impl Stats {
+    pub fn singleton(value: i64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    pub fn update(&mut self, value: i64) {
+        self.merge(Self::singleton(value))
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        self.min = i64::min(self.min, other.min);
+        self.max = i64::max(self.max, other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    fn avg(&self) -> f64 {
+        self.sum as f64 / 10f64.powi(SCALE as i32) / self.count as f64
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Note: this rounds to nearest.
+        //
+        // The challenge rules say to round away from zero (the opposite of
+        // truncate), but their example code doesn't do what they say -- it
+        // rounds to nearest.
+        let scale = 10f64.powi(SCALE as i32);
+        write!(
+            f,
+            "{:.1}/{:.1}/{:.1}",
+            self.min as f64 / scale,
+            self.avg(),
+            self.max as f64 / scale
+        )
+    }
+}